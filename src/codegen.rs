@@ -4,9 +4,99 @@ use inkwell::context::Context;
 use inkwell::module::{Linkage, Module};
 use inkwell::targets::TargetData;
 use inkwell::values::{FunctionValue, PointerValue};
-use inkwell::{AddressSpace, IntPredicate};
+use inkwell::{AddressSpace, IntPredicate, OptimizationLevel};
 use inkwell::passes::PassManager;
 
+/// A run-length-folded Brainfuck instruction. Runs of `+`/`-`, `<`/`>` and `.`
+/// collapse into a single op with a count so a run like `+++++` becomes one
+/// `Add(5)` instead of five read-modify-write sequences; each `,` stays a
+/// separate `Input`.
+enum Op {
+    Add(i8),
+    Move(i32),
+    Output(u32),
+    Input,
+    Clear,
+    LoopStart,
+    LoopEnd,
+}
+
+/// Fold a Brainfuck source string into the compact [`Op`] stream, run-length
+/// encoding `+`/`-` and `<`/`>` runs, collapsing `[-]`/`[+]` clear loops into a
+/// single [`Op::Clear`], and matching brackets. Returns an error on an
+/// unbalanced `[` or `]`.
+fn parse_ops(code: &str) -> Result<Vec<Op>, String> {
+    let bytes = code.as_bytes();
+    let mut ops = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' | b'-' => {
+                let mut amount: i8 = 0;
+                while i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+                    amount = amount.wrapping_add(if bytes[i] == b'+' { 1 } else { -1 });
+                    i += 1;
+                }
+                if amount != 0 {
+                    ops.push(Op::Add(amount));
+                }
+            }
+            b'>' | b'<' => {
+                let mut amount: i32 = 0;
+                while i < bytes.len() && (bytes[i] == b'>' || bytes[i] == b'<') {
+                    amount += if bytes[i] == b'>' { 1 } else { -1 };
+                    i += 1;
+                }
+                if amount != 0 {
+                    ops.push(Op::Move(amount));
+                }
+            }
+            b'.' => {
+                let mut count = 0u32;
+                while i < bytes.len() && bytes[i] == b'.' {
+                    count += 1;
+                    i += 1;
+                }
+                ops.push(Op::Output(count));
+            }
+            b',' => {
+                ops.push(Op::Input);
+                i += 1;
+            }
+            b'[' => {
+                if i + 2 < bytes.len()
+                    && (bytes[i + 1] == b'-' || bytes[i + 1] == b'+')
+                    && bytes[i + 2] == b']'
+                {
+                    ops.push(Op::Clear);
+                    i += 3;
+                } else {
+                    ops.push(Op::LoopStart);
+                    depth += 1;
+                    i += 1;
+                }
+            }
+            b']' => {
+                if depth == 0 {
+                    return Err("unbalanced ']'".to_string());
+                }
+                depth -= 1;
+                ops.push(Op::LoopEnd);
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    if depth != 0 {
+        return Err("unbalanced '['".to_string());
+    }
+
+    Ok(ops)
+}
+
 struct Codegen<'ctx> {
     ctx: &'ctx Context,
     builder: Builder<'ctx>,
@@ -73,32 +163,24 @@ impl<'ctx> Codegen<'ctx> {
         }
     }
 
-    fn gen_move_right(&mut self) {
-        let counter = self.builder.build_load(self.counter, "mr_counter_load")
-            .into_int_value();
-        let one = self.ctx.i32_type().const_int(1, false);
-
-        let counter = self.builder.build_int_add(counter, one, "inc_counter");
-
-        self.builder.build_store(self.counter, counter);
-    }
-
-    fn gen_move_left(&mut self) {
-        let counter = self.builder.build_load(self.counter, "ml_counter_load")
+    fn gen_move(&mut self, amount: i32) {
+        let counter = self.builder.build_load(self.counter, "mv_counter_load")
             .into_int_value();
-        let one = self.ctx.i32_type().const_int(1, false);
+        let amt = self.ctx.i32_type().const_int(amount as u64, true);
 
-        let counter = self.builder.build_int_sub(counter, one, "dec_counter");
+        let counter = self.builder.build_int_add(counter, amt, "mv_counter");
 
         self.builder.build_store(self.counter, counter);
     }
 
-    fn gen_output(&self) {
+    fn gen_output(&self, count: u32) {
         let cell_val = self.builder.build_load(self.get_cell_ptr(), "outp_load_cell")
             .into_int_value();
         let cell_val = self.builder.build_int_z_extend(cell_val, self.ctx.i32_type(), "outp_zero_ext");
 
-        self.builder.build_call(self.fns.putchar, &[cell_val.into()], "putchar");
+        for _ in 0..count {
+            self.builder.build_call(self.fns.putchar, &[cell_val.into()], "putchar");
+        }
     }
 
     fn gen_input(&self) {
@@ -115,28 +197,21 @@ impl<'ctx> Codegen<'ctx> {
         self.builder.build_store(cell_ptr, inp);
     }
 
-    fn gen_increment_cell(&self) {
+    fn gen_add(&self, amount: i8) {
         let val_ptr = self.get_cell_ptr();
         let val = self.builder
-            .build_load(val_ptr, "inc_load_cell")
+            .build_load(val_ptr, "add_load_cell")
             .into_int_value();
 
-        let val = self.builder
-            .build_int_add(val, self.ctx.i8_type().const_int(1, false), "inc_value");
+        let amt = self.ctx.i8_type().const_int(amount as u64, true);
+        let val = self.builder.build_int_add(val, amt, "add_value");
 
         self.builder.build_store(val_ptr, val);
     }
 
-    fn gen_decrement_cell(&self) {
+    fn gen_clear(&self) {
         let val_ptr = self.get_cell_ptr();
-        let val = self.builder
-            .build_load(val_ptr, "inc_load_cell")
-            .into_int_value();
-
-        let val = self.builder
-            .build_int_sub(val, self.ctx.i8_type().const_int(1, false), "dec_value");
-
-        self.builder.build_store(val_ptr, val);
+        self.builder.build_store(val_ptr, self.ctx.i8_type().const_zero());
     }
 
     fn gen_loop_entry(&mut self, func: FunctionValue) {
@@ -168,7 +243,7 @@ impl<'ctx> Codegen<'ctx> {
         self.builder.build_return(Some(&zero));
     }
 
-    pub fn generate(&mut self, heap_size: u64, code: &str) {
+    pub fn generate(&mut self, heap_size: u64, ops: &[Op]) {
         self.gen_startup(heap_size);
 
         let func = self.builder
@@ -177,17 +252,15 @@ impl<'ctx> Codegen<'ctx> {
             .get_parent()
             .unwrap();
 
-        for ch in code.chars() {
-            match ch {
-                '>' => self.gen_move_right(),
-                '<' => self.gen_move_left(),
-                '+' => self.gen_increment_cell(),
-                '-' => self.gen_decrement_cell(),
-                '.' => self.gen_output(),
-                ',' => self.gen_input(),
-                '[' => self.gen_loop_entry(func),
-                ']' => self.gen_loop_end(func),
-                _ => {}
+        for op in ops {
+            match op {
+                Op::Move(amount) => self.gen_move(*amount),
+                Op::Add(amount) => self.gen_add(*amount),
+                Op::Output(count) => self.gen_output(*count),
+                Op::Input => self.gen_input(),
+                Op::Clear => self.gen_clear(),
+                Op::LoopStart => self.gen_loop_entry(func),
+                Op::LoopEnd => self.gen_loop_end(func),
             }
         }
 
@@ -195,27 +268,41 @@ impl<'ctx> Codegen<'ctx> {
     }
 }
 
-fn run_passes(module: &Module) {
+fn run_passes(module: &Module, opt_level: OptimizationLevel) {
     let passes = PassManager::create(());
 
+    // Keep `-O0` faithful: promote allocas to registers so the IR is readable,
+    // then only verify. Everything above that layers on whole-module passes.
     passes.add_promote_memory_to_register_pass();
-    passes.add_constant_merge_pass();
-    passes.add_dead_arg_elimination_pass();
-    passes.add_global_optimizer_pass();
-    passes.add_strip_symbol_pass();
-    passes.add_loop_vectorize_pass();
-    passes.add_aggressive_dce_pass();
-    passes.add_dead_store_elimination_pass();
-    passes.add_scalarizer_pass();
-    passes.add_merged_load_store_motion_pass();
-    passes.add_new_gvn_pass();
-    passes.add_ind_var_simplify_pass();
-    passes.add_instruction_combining_pass();
-    passes.add_cfg_simplification_pass();
-    passes.add_loop_deletion_pass();
-    passes.add_loop_unroll_pass();
-    passes.add_licm_pass();
-    passes.add_reassociate_pass();
+
+    let level = opt_level as u8;
+
+    if level >= OptimizationLevel::Less as u8 {
+        passes.add_instruction_combining_pass();
+        passes.add_cfg_simplification_pass();
+        passes.add_new_gvn_pass();
+        passes.add_reassociate_pass();
+        passes.add_aggressive_dce_pass();
+    }
+
+    if level >= OptimizationLevel::Default as u8 {
+        passes.add_constant_merge_pass();
+        passes.add_dead_arg_elimination_pass();
+        passes.add_global_optimizer_pass();
+        passes.add_dead_store_elimination_pass();
+        passes.add_merged_load_store_motion_pass();
+        passes.add_ind_var_simplify_pass();
+        passes.add_loop_deletion_pass();
+        passes.add_licm_pass();
+    }
+
+    if level >= OptimizationLevel::Aggressive as u8 {
+        passes.add_loop_vectorize_pass();
+        passes.add_scalarizer_pass();
+        passes.add_loop_unroll_pass();
+        passes.add_strip_symbol_pass();
+    }
+
     passes.add_verifier_pass();
 
     passes.run_on(module);
@@ -227,7 +314,10 @@ pub fn compile_module<'a>(
     name: &str,
     heap_size: u64,
     code: &str,
-) -> Module<'a> {
+    opt_level: OptimizationLevel,
+) -> Result<Module<'a>, String> {
+    let ops = parse_ops(code)?;
+
     let module = ctx.create_module(name);
     let builder = ctx.create_builder();
 
@@ -246,9 +336,9 @@ pub fn compile_module<'a>(
         loop_stack: Vec::new(),
     };
 
-    codegen.generate(heap_size, code);
+    codegen.generate(heap_size, &ops);
 
-    run_passes(&module);
+    run_passes(&module, opt_level);
 
-    module
+    Ok(module)
 }
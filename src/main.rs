@@ -9,11 +9,12 @@ use clap::{App, Arg, ArgMatches};
 use inkwell::context::Context;
 use inkwell::support::LLVMString;
 use inkwell::targets::{
-    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
 };
 use inkwell::OptimizationLevel;
 
 mod codegen;
+mod vm;
 
 fn parse_args() -> ArgMatches {
     App::new("Brainfucker")
@@ -26,6 +27,9 @@ fn parse_args() -> ArgMatches {
         .arg(Arg::new("compile").short('c')
             .long("compile")
             .about("Create object file only"))
+        .arg(Arg::new("run").short('r')
+            .long("run")
+            .about("JIT-compile and run the program in-process"))
         .arg(Arg::new("output")
             .short('o')
             .long("output")
@@ -38,6 +42,36 @@ fn parse_args() -> ArgMatches {
             .takes_value(true)
             .value_name("LEVEL")
             .about("Sets optimization level 0-3 (default 2)"))
+        .arg(Arg::new("backend")
+            .long("backend")
+            .takes_value(true)
+            .value_name("BACKEND")
+            .possible_values(&["llvm", "vm"])
+            .about("Selects the lowering backend (default llvm)"))
+        .arg(Arg::new("disasm")
+            .long("disasm")
+            .about("Print the bytecode disassembly (vm backend only)"))
+        .arg(Arg::new("target")
+            .long("target")
+            .takes_value(true)
+            .value_name("TRIPLE")
+            .about("Cross-compile for the given target triple"))
+        .arg(Arg::new("cpu")
+            .long("cpu")
+            .takes_value(true)
+            .value_name("CPU")
+            .about("Sets the target CPU"))
+        .arg(Arg::new("features")
+            .long("features")
+            .takes_value(true)
+            .value_name("FEATURES")
+            .about("Sets the target feature string"))
+        .arg(Arg::new("emit")
+            .long("emit")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .possible_values(&["obj", "asm", "ir", "bc"])
+            .about("Sets the emitted artifact format (default obj)"))
         .arg(Arg::new("heap_size")
             .short('s')
             .long("heap-size")
@@ -74,7 +108,12 @@ impl Display for LLVMError {
 
 impl Error for LLVMError {}
 
-fn init_llvm(opt_level: OptimizationLevel) -> anyhow::Result<TargetMachine> {
+fn init_llvm(
+    opt_level: OptimizationLevel,
+    triple: Option<&str>,
+    cpu: Option<&str>,
+    features: Option<&str>,
+) -> anyhow::Result<TargetMachine> {
     let config = InitializationConfig {
         asm_parser: false,
         asm_printer: true,
@@ -83,16 +122,27 @@ fn init_llvm(opt_level: OptimizationLevel) -> anyhow::Result<TargetMachine> {
         info: false,
         machine_code: true,
     };
-    Target::initialize_native(&config).map_err(LLVMError::from)?;
 
-    let triple = TargetMachine::get_default_triple();
+    // Only the host backend is needed when building for the default triple; an
+    // explicit --target may name any architecture, so initialize all of them.
+    let (triple, cpu) = match triple {
+        Some(triple) => {
+            Target::initialize_all(&config);
+            (TargetTriple::create(triple), cpu.unwrap_or(""))
+        }
+        None => {
+            Target::initialize_native(&config).map_err(LLVMError::from)?;
+            (TargetMachine::get_default_triple(), cpu.unwrap_or("generic"))
+        }
+    };
+
     let target = Target::from_triple(&triple).map_err(LLVMError::from)?;
 
     let target_machine = target
         .create_target_machine(
             &triple,
-            "generic",
-            "",
+            cpu,
+            features.unwrap_or(""),
             opt_level,
             RelocMode::Default,
             CodeModel::Default,
@@ -128,18 +178,92 @@ fn main() -> anyhow::Result<()> {
     let input_name = input.file_stem().context("invalid input path")?.to_str().unwrap();
     let input = fs::read_to_string(input).context("could not read input file")?;
 
-    let target_machine = init_llvm(opt_level)?;
+    if args.value_of("backend").unwrap_or("llvm") == "vm" {
+        let ops = vm::compile(&input).map_err(LLVMError::from)?;
+
+        if args.is_present("disasm") {
+            print!("{}", vm::disassemble(&ops));
+        } else {
+            vm::interpret(&ops, heap_size as usize);
+        }
+
+        return Ok(());
+    }
+
+    let target = args.value_of("target");
+    let target_machine = init_llvm(
+        opt_level,
+        target,
+        args.value_of("cpu"),
+        args.value_of("features"),
+    )?;
     let target_data = target_machine.get_target_data();
     let ctx = Context::create();
 
-    let module = codegen::compile_module(&ctx, &target_data, &input_name, heap_size, &input);
+    let module = codegen::compile_module(&ctx, &target_data, &input_name, heap_size, &input, opt_level)
+        .map_err(LLVMError::from)?;
+
+    if args.is_present("run") {
+        // The JIT runs the generated code inside this process, so it only works
+        // for the host architecture.
+        if args.value_of("target").is_some() {
+            anyhow::bail!("--run cannot be combined with --target");
+        }
+
+        let engine = module
+            .create_jit_execution_engine(opt_level)
+            .map_err(LLVMError::from)?;
+
+        // The generated code calls into libc (putchar/getchar/calloc/free), whose
+        // symbols resolve against the host process, so no CRT files or linker
+        // are needed here.
+        let code = unsafe {
+            let main = engine
+                .get_function::<unsafe extern "C" fn() -> i32>("main")
+                .context("cannot find main in JIT module")?;
+            main.call()
+        };
+
+        std::process::exit(code);
+    }
+
+    match args.value_of("emit").unwrap_or("obj") {
+        "asm" => {
+            let asm_path = Path::new(args.value_of("output").unwrap_or(input_name))
+                .with_extension("s");
+            target_machine
+                .write_to_file(&module, FileType::Assembly, &asm_path)
+                .map_err(LLVMError::from)?;
+            return Ok(());
+        }
+        "ir" => {
+            let ir_path = Path::new(args.value_of("output").unwrap_or(input_name))
+                .with_extension("ll");
+            module
+                .print_to_file(&ir_path)
+                .map_err(LLVMError::from)?;
+            return Ok(());
+        }
+        "bc" => {
+            let bc_path = Path::new(args.value_of("output").unwrap_or(input_name))
+                .with_extension("bc");
+            if !module.write_bitcode_to_path(&bc_path) {
+                anyhow::bail!("could not write bitcode to file");
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
 
     let obj_path = Path::new(&input_name).with_extension("o");
     target_machine
         .write_to_file(&module, FileType::Object, &obj_path)
         .map_err(LLVMError::from)?;
 
-    if !args.is_present("compile") {
+    // The link step below hardcodes x86-64 Linux CRT objects and dynamic
+    // linker, so it only makes sense when building for the host. For any
+    // explicit --target, leave the object file in place instead.
+    if !args.is_present("compile") && target.is_none() {
         let out_name = args.value_of("output").unwrap_or(input_name);
 
         let status = Command::new("ld")
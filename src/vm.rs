@@ -0,0 +1,153 @@
+use std::io::{Read, Write};
+
+/// A portable bytecode instruction. Loops are lowered to explicit jumps whose
+/// targets are resolved to instruction indices, so the interpreter needs no
+/// bracket matching at run time.
+pub enum Op {
+    AddCell(i8),
+    MovePtr(i32),
+    Out(u32),
+    In,
+    JumpIfZero(usize),
+    JumpIfNonZero(usize),
+}
+
+/// Compile a Brainfuck source string into the bytecode, run-length encoding
+/// `+`/`-`, `<`/`>` and `.` runs and resolving bracket targets during a
+/// balancing pass. Returns an error on an unbalanced `[` or `]`.
+pub fn compile(code: &str) -> Result<Vec<Op>, String> {
+    let bytes = code.as_bytes();
+    let mut ops = Vec::new();
+    let mut stack = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' | b'-' => {
+                let mut amount: i8 = 0;
+                while i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+                    amount = amount.wrapping_add(if bytes[i] == b'+' { 1 } else { -1 });
+                    i += 1;
+                }
+                if amount != 0 {
+                    ops.push(Op::AddCell(amount));
+                }
+            }
+            b'>' | b'<' => {
+                let mut amount: i32 = 0;
+                while i < bytes.len() && (bytes[i] == b'>' || bytes[i] == b'<') {
+                    amount += if bytes[i] == b'>' { 1 } else { -1 };
+                    i += 1;
+                }
+                if amount != 0 {
+                    ops.push(Op::MovePtr(amount));
+                }
+            }
+            b'.' => {
+                let mut count = 0u32;
+                while i < bytes.len() && bytes[i] == b'.' {
+                    count += 1;
+                    i += 1;
+                }
+                ops.push(Op::Out(count));
+            }
+            b',' => {
+                ops.push(Op::In);
+                i += 1;
+            }
+            b'[' => {
+                stack.push(ops.len());
+                ops.push(Op::JumpIfZero(0)); // target patched at the matching ']'
+                i += 1;
+            }
+            b']' => {
+                let open = stack.pop().ok_or_else(|| "unbalanced ']'".to_string())?;
+                let close = ops.len();
+                ops.push(Op::JumpIfNonZero(open + 1));
+                ops[open] = Op::JumpIfZero(close + 1);
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err("unbalanced '['".to_string());
+    }
+
+    Ok(ops)
+}
+
+/// Walk the bytecode over a fresh `heap_size`-byte tape, using stdout/stdin for
+/// `.`/`,`. Cell values wrap on overflow like the compiled backend. The cell
+/// pointer, however, wraps modulo the tape length — this is a VM-only guarantee:
+/// the LLVM backend instead walks off the tape into undefined behavior, so a
+/// program that moves past cell 0 or past the end behaves differently under the
+/// two backends. `heap_size` must be non-zero.
+pub fn interpret(ops: &[Op], heap_size: usize) {
+    assert!(heap_size > 0, "heap size must be greater than zero");
+
+    let mut tape = vec![0u8; heap_size];
+    let mut ptr = 0usize;
+    let mut pc = 0usize;
+
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let mut input = stdin.lock();
+    let mut output = stdout.lock();
+
+    while pc < ops.len() {
+        match ops[pc] {
+            Op::AddCell(amount) => {
+                tape[ptr] = tape[ptr].wrapping_add(amount as u8);
+                pc += 1;
+            }
+            Op::MovePtr(amount) => {
+                ptr = (ptr as i64 + amount as i64).rem_euclid(tape.len() as i64) as usize;
+                pc += 1;
+            }
+            Op::Out(count) => {
+                for _ in 0..count {
+                    output.write_all(&[tape[ptr]]).expect("cannot write to stdout");
+                }
+                pc += 1;
+            }
+            Op::In => {
+                let mut buf = [0u8; 1];
+                tape[ptr] = match input.read(&mut buf) {
+                    Ok(0) | Err(_) => 0,
+                    Ok(_) => buf[0],
+                };
+                pc += 1;
+            }
+            Op::JumpIfZero(target) => {
+                pc = if tape[ptr] == 0 { target } else { pc + 1 };
+            }
+            Op::JumpIfNonZero(target) => {
+                pc = if tape[ptr] != 0 { target } else { pc + 1 };
+            }
+        }
+    }
+
+    output.flush().expect("cannot flush stdout");
+}
+
+/// Render the bytecode as a human-readable listing with instruction offsets and
+/// resolved jump targets.
+pub fn disassemble(ops: &[Op]) -> String {
+    let mut out = String::new();
+
+    for (offset, op) in ops.iter().enumerate() {
+        let line = match op {
+            Op::AddCell(amount) => format!("add   {}", amount),
+            Op::MovePtr(amount) => format!("move  {}", amount),
+            Op::Out(count) => format!("out   {}", count),
+            Op::In => "in".to_string(),
+            Op::JumpIfZero(target) => format!("jz    {}", target),
+            Op::JumpIfNonZero(target) => format!("jnz   {}", target),
+        };
+        out.push_str(&format!("{:04}: {}\n", offset, line));
+    }
+
+    out
+}